@@ -3,11 +3,14 @@
 //! Generates weighted-probability of pre-defined math problems with increased probability of selection based on frequency of incorrect answers and amount of times previously presented as well as the time required to correctly answer the most recent time
 
 use std::fmt;
+use std::collections::HashMap;
 use rand::Rng;
-use rand::distributions::{Distribution, Uniform};
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
 
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
 /// MathOp
 /// 
 /// Mathematical operators
@@ -16,7 +19,7 @@ pub enum MathOp {
     Plus,
     Minus,
     Multiply,
-    //Divide
+    Divide
 }
 
 impl std::fmt::Display for MathOp {
@@ -25,7 +28,7 @@ impl std::fmt::Display for MathOp {
             MathOp::Plus => "+",
             MathOp::Minus => "-",
             MathOp::Multiply => "x",
-            //MathOp::Divide => "\u{00f7}"            
+            MathOp::Divide => "\u{00f7}"
         });
     }
 }
@@ -37,11 +40,75 @@ impl std::fmt::Display for MathOp {
 /// In addition, it stores the time in seconds required to answer the problem
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Problem {
-    operands: [u8;2],
+    operands: [u16;2],
     operator: MathOp,
-    answer: u8,
-    num_wrong: u16,    
-    latest_time: Duration
+    answer: u16,
+    num_wrong: u16,
+    latest_time: Duration,
+    /// SM-2 easiness factor, never allowed below 1.3
+    #[serde(default = "default_ef")]
+    ef: f32,
+    /// Number of successful repetitions in the current streak
+    #[serde(default)]
+    reps: u32,
+    /// Current inter-review interval in days
+    #[serde(default = "default_interval")]
+    interval: u32,
+    /// When this problem was last reviewed, used to compute the due date
+    #[serde(default = "default_last_reviewed")]
+    last_reviewed: SystemTime,
+    /// Difficulty tier this problem was generated from
+    #[serde(default)]
+    tier: usize
+}
+
+/// Defaults for the spaced-repetition fields, used when loading older progress
+/// files written before these fields existed.
+fn default_ef() -> f32 { 2.5 }
+fn default_interval() -> u32 { 1 }
+fn default_last_reviewed() -> SystemTime { UNIX_EPOCH }
+
+/// A single difficulty tier for one [`MathOp`]
+///
+/// Problems are generated with operands in `min..=max` (for division, the
+/// divisor and the quotient). The tier is considered mastered - and the next
+/// one unlocked - once every problem in it reaches an `interval` of at least
+/// `unlock_threshold` days; `num_wrong` is not taken into account.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tier {
+    pub min: u16,
+    pub max: u16,
+    pub unlock_threshold: u32,
+}
+
+/// Difficulty configuration deserialized from the `--config` file: an ordered
+/// list of tiers per operator, from easiest to hardest.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Config {
+    pub tiers: HashMap<MathOp, Vec<Tier>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut tiers = HashMap::new();
+        tiers.insert(MathOp::Plus, vec![
+            Tier { min: 0, max: 10, unlock_threshold: 6 },
+            Tier { min: 0, max: 20, unlock_threshold: 6 },
+        ]);
+        tiers.insert(MathOp::Minus, vec![
+            Tier { min: 0, max: 10, unlock_threshold: 6 },
+            Tier { min: 0, max: 20, unlock_threshold: 6 },
+        ]);
+        tiers.insert(MathOp::Multiply, vec![
+            Tier { min: 1, max: 5, unlock_threshold: 6 },
+            Tier { min: 1, max: 12, unlock_threshold: 6 },
+        ]);
+        tiers.insert(MathOp::Divide, vec![
+            Tier { min: 1, max: 5, unlock_threshold: 6 },
+            Tier { min: 1, max: 9, unlock_threshold: 6 },
+        ]);
+        return Self { tiers };
+    }
 }
 
 /*
@@ -56,18 +123,25 @@ impl std::fmt::Display for Problem {
 }
 
 impl Problem {
-    pub fn new(operands: [u8;2], operator: MathOp, num_wrong: u16, latest_time: Duration) -> Self {        
-        Self {  
-            operands,             
+    pub fn new(operands: [u16;2], operator: MathOp, num_wrong: u16, latest_time: Duration) -> Self {
+        Self {
+            operands,
             answer: match operator {
                     MathOp::Plus => operands[0]+operands[1],
                     MathOp::Minus => operands[0]-operands[1],
-                    MathOp::Multiply => operands[0]*operands[1] 
+                    MathOp::Multiply => operands[0]*operands[1],
+                    // Guard against a zero divisor so construction can never panic
+                    MathOp::Divide => if operands[1]==0 {0} else {operands[0]/operands[1]}
                 },
             operator,
-            num_wrong,             
-            latest_time }
-    } 
+            num_wrong,
+            latest_time,
+            ef: 2.5,
+            reps: 0,
+            interval: 1,
+            last_reviewed: UNIX_EPOCH,
+            tier: 0 }
+    }
 
     fn get_score(&self) -> f32 {
         return self.num_wrong as f32 * 30.0 + self.latest_time.as_secs() as f32;
@@ -77,87 +151,329 @@ impl Problem {
         return self.operator;
     }
 
+    /// Next time this problem is due for review, i.e. `last_reviewed + interval days`
+    fn due_date(&self) -> SystemTime {
+        return self.last_reviewed + Duration::from_secs(self.interval as u64 * 86400);
+    }
+
+    /// Whether the problem is overdue relative to `now`
+    fn is_overdue(&self, now: SystemTime) -> bool {
+        return now >= self.due_date();
+    }
+
+    /// Apply one SM-2 update for an answer graded `q` in 0..=5
+    fn schedule(&mut self, q: u8) {
+        if q < 3 {
+            self.reps = 0;
+            self.interval = 1;
+        } else {
+            self.reps += 1;
+            self.interval = match self.reps {
+                1 => 1,
+                2 => 6,
+                _ => (self.interval as f32 * self.ef).round() as u32,
+            };
+        }
+        // Adjust easiness, never dropping below the SM-2 floor of 1.3
+        let q = q as f32;
+        self.ef += 0.1 - (5.0 - q) * (0.08 + (5.0 - q) * 0.02);
+        if self.ef < 1.3 {
+            self.ef = 1.3;
+        }
+        self.last_reviewed = SystemTime::now();
+    }
+
     pub fn check_guess(&mut self, guess: u16, elapsed_time: Duration) -> bool {
-        if self.answer as u16==guess {
-            // Store time required to answer correctly            
+        if self.answer==guess {
+            // Store time required to answer correctly
             self.latest_time=elapsed_time;
             if self.num_wrong>1 {self.num_wrong-=1;}
             return true;
-        } else {            
+        } else {
             self.num_wrong+=1;
             return false;
         }
     }
 
+    /// Run a single SM-2 update for a whole presentation. Grading happens once,
+    /// not per retry: `first_try` is whether the first answer was correct and
+    /// `elapsed` is the time to that first answer, so a fact missed once is
+    /// graded as a lapse (q < 3) rather than re-penalized for every wrong retry.
+    pub fn grade(&mut self, first_try: bool, elapsed: Duration) {
+        // Correct-and-fast scores highest, a slow first answer still passes, and
+        // any initial mistake fails the review.
+        let secs = elapsed.as_secs();
+        let q: u8 = if first_try {
+            if secs <= 2 { 5 } else if secs <= 5 { 4 } else { 3 }
+        } else if secs <= 5 { 2 } else { 1 };
+        self.schedule(q);
+    }
+
     pub fn get_time(&self) -> Duration {
         return self.latest_time;
     }
+
+    /// Whether this problem has been drilled to the tier's mastery bar. The SM-2
+    /// `interval` already encodes retention (it only grows past a few days after
+    /// several clean repetitions), so it is the bar on its own - using
+    /// `num_wrong` as well would pin any once-missed fact below mastery forever.
+    fn is_mastered(&self, unlock_threshold: u32) -> bool {
+        return self.interval >= unlock_threshold;
+    }
+
+    /// Estimated probability that the student answers this problem correctly,
+    /// derived as a logistic of `get_score`: a low score (mastered, quick) maps
+    /// near 1.0, a high score (often wrong, slow) maps near 0.0.
+    ///
+    /// Note this is a difficulty proxy, not a history-based estimate: a freshly
+    /// generated problem (`num_wrong == 0`, low seed time) maps to a high `p` and
+    /// is therefore *deliberately deprioritized* in challenge mode until it has
+    /// actually been missed. The per-attempt history recorded by the `sqlite`
+    /// backend (`problem_history`) could drive a recent-correct-ratio estimator
+    /// instead, but challenge mode intentionally drills known-shaky facts rather
+    /// than never-seen ones.
+    fn success_prob(&self) -> f32 {
+        // Centre the logistic so a "middling" score lands at p = 0.5. The spread
+        // is kept small so a low score genuinely maps p -> ~1 (a mastered fact is
+        // almost always answered correctly), driving its info_weight near zero.
+        const MID: f32 = 30.0;
+        const SPREAD: f32 = 6.0;
+        return 1.0 / (1.0 + ((self.get_score() - MID) / SPREAD).exp());
+    }
+
+    /// Information weight `p * (1 - p)`, maximized when the outcome is most
+    /// uncertain (`p = 0.5`) and near zero for mastered or never-mastered items.
+    fn info_weight(&self) -> f32 {
+        let p = self.success_prob();
+        return p * (1.0 - p);
+    }
+}
+
+/// Initialize a problem set at the starting (tier 0) difficulty for every
+/// operator described in `config`.
+pub fn init_problems(problems: &mut Vec<Problem>, config: &Config) {
+    add_addition(problems, config);
+    add_subtraction(problems, config);
+    add_mult(problems, config);
+    return add_division(problems, config);
 }
 
-/// Initialize a problem set
-/// Start with addition from 0..10
-/// Then add subtraction from 0..10 with only non-negative results
-pub fn init_problems(problems: &mut Vec<Problem>) {
-    add_addition(problems);
-    add_subtraction(problems);
-    return add_mult(problems);
+/// Seed time (how long a fresh problem is assumed to take) per operator
+fn seed_time(op: MathOp) -> Duration {
+    return match op {
+        MathOp::Plus => Duration::from_secs(5),
+        MathOp::Minus => Duration::from_secs(10),
+        MathOp::Multiply | MathOp::Divide => Duration::from_secs(15),
+    };
 }
 
-/// Add basic addition problems
-pub fn add_addition(problems: &mut Vec<Problem>) {
-    // Start with addition problems of 0..10
-    for x in 1..=15 {
-        for y in 0..=13 {
-            problems.push(Problem::new([x, y], MathOp::Plus, 0, Duration::from_secs(5)));
-            if x!=y {
-                problems.push(Problem::new([y, x], MathOp::Plus, 0, Duration::from_secs(5)));
+/// Generate every problem for `op` at the given tier index, tagging each with
+/// that tier so later sessions can tell which tier a problem belongs to.
+fn generate_tier(problems: &mut Vec<Problem>, op: MathOp, tier_idx: usize, tier: &Tier) {
+    let seed = seed_time(op);
+    let mut push = |operands: [u16;2]| {
+        let mut p = Problem::new(operands, op, 0, seed);
+        p.tier = tier_idx;
+        problems.push(p);
+    };
+    match op {
+        MathOp::Plus => {
+            for x in tier.min..=tier.max {
+                for y in tier.min..=tier.max {
+                    push([x, y]);
+                }
+            }
+        }
+        MathOp::Minus => {
+            for x in tier.min..=tier.max {
+                for y in tier.min..x {
+                    push([x, y]);
+                }
             }
         }
-    }   
+        MathOp::Multiply => {
+            for x in tier.min..=tier.max {
+                for y in tier.min..=tier.max {
+                    push([x, y]);
+                }
+            }
+        }
+        MathOp::Divide => {
+            // Draw from the multiplication table so every quotient is exact:
+            // for divisor `d` and quotient `q` the problem is `d*q \u{00f7} d = q`.
+            for d in tier.min.max(1)..=tier.max {
+                for q in tier.min.max(1)..=tier.max {
+                    push([d*q, d]);
+                }
+            }
+        }
+    }
 }
 
-/// Add basic subtraction problems
-pub fn add_subtraction(problems: &mut Vec<Problem>) {
-    // Start with basic subtractions problems of 0..10
-    for x in 0..=15 {
-        for y in 1..x {
-            problems.push(Problem::new([x,y], MathOp::Minus, 0, Duration::from_secs(10)));
-        }
-    }    
+/// Generate the currently-unlocked tier for `op` if none of its problems exist yet.
+fn add_unlocked(problems: &mut Vec<Problem>, op: MathOp, config: &Config) {
+    let tiers = match config.tiers.get(&op) {
+        Some(tiers) if !tiers.is_empty() => tiers,
+        _ => return,
+    };
+    if problems.iter().any(|p| p.operator == op) {
+        return;
+    }
+    generate_tier(problems, op, 0, &tiers[0]);
 }
 
-/// Add basic multiplication problems
-pub fn add_mult(problems: &mut Vec<Problem>) {
-    // Start with basic subtractions problems of 0..10
-    for x in 1..=5 {
-        for y in 1..=3 {
-            problems.push(Problem::new([x,y], MathOp::Multiply, 0, Duration::from_secs(15)));
+/// Add addition problems for the currently-unlocked tier
+pub fn add_addition(problems: &mut Vec<Problem>, config: &Config) {
+    add_unlocked(problems, MathOp::Plus, config);
+}
+
+/// Add subtraction problems for the currently-unlocked tier
+pub fn add_subtraction(problems: &mut Vec<Problem>, config: &Config) {
+    add_unlocked(problems, MathOp::Minus, config);
+}
+
+/// Add multiplication problems for the currently-unlocked tier
+pub fn add_mult(problems: &mut Vec<Problem>, config: &Config) {
+    add_unlocked(problems, MathOp::Multiply, config);
+}
+
+/// Add division problems for the currently-unlocked tier
+pub fn add_division(problems: &mut Vec<Problem>, config: &Config) {
+    add_unlocked(problems, MathOp::Divide, config);
+}
+
+/// Unlock the next tier for any operator whose current tier is fully mastered.
+/// Called at session start so ranges expand automatically as facts are learned.
+pub fn expand_tiers(problems: &mut Vec<Problem>, config: &Config) {
+    for (op, tiers) in &config.tiers {
+        // Highest tier currently present for this operator
+        let cur = match problems.iter().filter(|p| p.operator == *op).map(|p| p.tier).max() {
+            Some(cur) => cur,
+            None => continue,
+        };
+        if cur + 1 >= tiers.len() {
+            continue;
         }
-    }    
+        let mastered = problems
+            .iter()
+            .filter(|p| p.operator == *op && p.tier == cur)
+            .all(|p| p.is_mastered(tiers[cur].unlock_threshold));
+        if mastered {
+            generate_tier(problems, *op, cur + 1, &tiers[cur + 1]);
+        }
+    }
 }
 
-pub fn select_problem(problems: &Vec<Problem>) -> usize {
-    // Compute maximum score
-    let max_score: f32 = problems.iter().map(|p| p.get_score()).sum();
+/// Pick one index out of `candidates` using the cumulative-sum walk, weighting
+/// each candidate by its `get_score`.
+fn select_weighted(problems: &[Problem], candidates: &[usize]) -> usize {
+    // Compute maximum score over the candidate set
+    let max_score: f32 = candidates.iter().map(|&p| problems[p].get_score()).sum();
     // Get random number from 0 to maximum_score, inclusive
     let pick = rand::thread_rng().gen_range(0.0..=max_score);
     // Now pick the problem
     let mut score: f32 = 0.0;
-    for p in 0..problems.len() {
+    for &p in candidates {
         score+=problems[p].get_score();
         if score>=pick {
             return p;
         }
     }
+    // Otherwise return final candidate
+    return candidates[candidates.len()-1];
+}
+
+/// Select the next problem to present. Overdue problems (whose SM-2 due date has
+/// passed) are preferred; only when none are overdue do we fall back to the usual
+/// score weighting across the remaining, not-yet-due problems.
+pub fn select_problem(problems: &Vec<Problem>) -> usize {
+    let now = SystemTime::now();
+    let overdue: Vec<usize> = (0..problems.len())
+        .filter(|&p| problems[p].is_overdue(now))
+        .collect();
+    if !overdue.is_empty() {
+        return select_weighted(problems, &overdue);
+    }
+    // Nothing is due yet - fall back to weighting over the full set
+    let all: Vec<usize> = (0..problems.len()).collect();
+    return select_weighted(problems, &all);
+}
+
+/// Select a problem in "challenge" mode by maximizing expected information gain:
+/// sample proportional to each problem's `info_weight`, so facts the student gets
+/// right or wrong about half the time - the most informative ones - come up most
+/// often, using the same cumulative-sum walk as [`select_problem`].
+pub fn select_problem_adaptive(problems: &[Problem]) -> usize {
+    let total: f32 = problems.iter().map(|p| p.info_weight()).sum();
+    let pick = rand::thread_rng().gen_range(0.0..=total);
+    let mut weight: f32 = 0.0;
+    for p in 0..problems.len() {
+        weight += problems[p].info_weight();
+        if weight >= pick {
+            return p;
+        }
+    }
     // Otherwise return final problem
     return problems.len()-1;
 }
 
-/// Sort problems for presentation serially using random process which favors incorrectly answered questions as well as
-/// quesions which took a long time to answer
-pub fn sort_problems(problems: &mut Vec<Problem>) {
-    let rng = Uniform::from(0..=10000);
-    
+/// Plan a full-session ordering of every problem as a weighted shuffle: problems
+/// are drawn without replacement with probability proportional to `get_score`
+/// (favoring often-wrong and slow facts), using the same cumulative-sum trick as
+/// [`select_problem`]. A sliding window of `N` (default 3) forbids the same
+/// problem or the same `MathOp` from recurring back-to-back; candidates that
+/// would violate it are skipped, and when the window cannot be satisfied we fall
+/// back to the best (highest-scoring) remaining candidate. Returns the ordering
+/// so the caller can present a pre-planned, non-repetitive sequence.
+pub fn sort_problems(problems: &[Problem]) -> Vec<usize> {
+    const WINDOW: usize = 3;
+    let mut rng = rand::thread_rng();
+    let mut remaining: Vec<usize> = (0..problems.len()).collect();
+    let mut order: Vec<usize> = Vec::with_capacity(problems.len());
+
+    while !remaining.is_empty() {
+        // Operators placed in the last WINDOW-1 slots are temporarily blocked
+        let recent: Vec<MathOp> = order
+            .iter()
+            .rev()
+            .take(WINDOW - 1)
+            .map(|&i| problems[i].get_op())
+            .collect();
+        // Positions into `remaining` that satisfy the window constraint
+        let allowed: Vec<usize> = (0..remaining.len())
+            .filter(|&k| !recent.contains(&problems[remaining[k]].get_op()))
+            .collect();
+
+        let chosen = if allowed.is_empty() {
+            // Window can't be satisfied - fall back to the best available candidate
+            (0..remaining.len())
+                .max_by(|&a, &b| {
+                    problems[remaining[a]]
+                        .get_score()
+                        .partial_cmp(&problems[remaining[b]].get_score())
+                        .unwrap()
+                })
+                .unwrap()
+        } else {
+            // Weighted draw (cumulative-sum walk) among the allowed candidates
+            let total: f32 = allowed.iter().map(|&k| problems[remaining[k]].get_score()).sum();
+            let pick = rng.gen_range(0.0..=total);
+            let mut acc: f32 = 0.0;
+            let mut chosen = allowed[allowed.len() - 1];
+            for &k in &allowed {
+                acc += problems[remaining[k]].get_score();
+                if acc >= pick {
+                    chosen = k;
+                    break;
+                }
+            }
+            chosen
+        };
+
+        order.push(remaining[chosen]);
+        remaining.swap_remove(chosen);
+    }
+    return order;
 }
 #[cfg(test)]
 mod tests {
@@ -181,4 +497,52 @@ mod tests {
         assert!(i32::abs(num_selected[1]/100000 - 23)<=1, "Expected 30% for second problem");
         assert!(i32::abs(num_selected[2]/100000 - 11)<=1, "Expected 10% for third problem");
     }
+
+    /// Adaptive selection should strongly prefer problems near p=0.5 over
+    /// mastered (p~1) or never-mastered (p~0) ones.
+    #[test]
+    fn test_select_adaptive() {
+        let mut num_selected: [i32;3] = [0, 0, 0];
+        let mut problems: Vec<Problem> = Vec::new();
+        // Mastered: low score -> p near 1 -> tiny information weight
+        problems.push(Problem::new([1,1],MathOp::Plus,0,Duration::from_secs(1)));
+        // Uncertain: score near the logistic midpoint -> p near 0.5 -> maximal weight
+        problems.push(Problem::new([7,6],MathOp::Plus,1,Duration::from_secs(0)));
+        // Never mastered: high score -> p near 0 -> tiny information weight
+        problems.push(Problem::new([9,8],MathOp::Plus,10,Duration::from_secs(0)));
+        for _rep in 0..1000000 {
+            num_selected[select_problem_adaptive(&problems)]+=1;
+        }
+        eprintln!("{:?}", num_selected);
+        assert!(num_selected[1] > num_selected[0] * 5, "Uncertain problem should dominate the mastered one");
+        assert!(num_selected[1] > num_selected[2] * 5, "Uncertain problem should dominate the never-mastered one");
+    }
+
+    /// A tier should unlock only once every problem in the active tier reaches
+    /// the mastery interval.
+    #[test]
+    fn test_tier_unlock() {
+        let mut tiers = std::collections::HashMap::new();
+        tiers.insert(MathOp::Multiply, vec![
+            Tier { min: 1, max: 2, unlock_threshold: 6 },
+            Tier { min: 1, max: 3, unlock_threshold: 6 },
+        ]);
+        let config = Config { tiers };
+
+        let mut problems: Vec<Problem> = Vec::new();
+        add_mult(&mut problems, &config);
+        let tier0_count = problems.len();
+
+        // Nothing is mastered yet, so no expansion should happen
+        expand_tiers(&mut problems, &config);
+        assert_eq!(problems.len(), tier0_count, "tier 1 must not unlock before tier 0 is mastered");
+
+        // Drill every tier-0 problem up to the mastery interval
+        for p in problems.iter_mut() {
+            p.interval = 6;
+        }
+        expand_tiers(&mut problems, &config);
+        assert!(problems.len() > tier0_count, "tier 1 should unlock once tier 0 is mastered");
+        assert!(problems.iter().any(|p| p.tier == 1), "tier-1 problems should have been added");
+    }
 }
\ No newline at end of file
@@ -2,7 +2,7 @@ use std::io::{self};
 use std::io::Write;
 use std::fs;
 use std::time::Instant;
-use math_quiz::Problem;
+use math_quiz::{Config, Problem};
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 use std::collections::HashMap;
@@ -17,6 +17,9 @@ struct Args {
     /// Reset progress
     #[arg(short, long, default_value_t=false)]
     reset: bool,
+    /// Selection mode: "review" (SM-2/overdue), "challenge" (adaptive information-gain), or "drill" (weighted-shuffle session)
+    #[arg(short, long, default_value = "review")]
+    mode: String,
     /// Add questions to question bank
     #[command(subcommand)]
     cmd: Option<Commands>,
@@ -36,35 +39,67 @@ fn main() -> std::io::Result<()> {
     // Parse command line arguments
     let args = Args::parse();
 
+    // Difficulty configuration drives the generated operand ranges
+    let config = load_config(&args.config);
+
     // List of problems
     let mut problems: Vec<Problem> = Vec::new();
 
-    // Attempt to load question bank from config file if specified
-    load_progress(&mut problems, &args.config);
+    // Open the history database when the sqlite backend is enabled; in that build
+    // it is the source of truth, replacing the JSON progress blob entirely.
+    #[cfg(feature = "sqlite")]
+    let conn = {
+        let conn = rusqlite::Connection::open("math_quiz.db").expect("Unable to open history database");
+        math_quiz::sqlite::migrate(&conn).expect("Unable to apply migrations");
+        conn
+    };
+
+    // Load existing progress from the active store
+    #[cfg(feature = "sqlite")]
+    {
+        problems = math_quiz::sqlite::load_progress(&conn).expect("Unable to load problems from database");
+        if problems.is_empty() {
+            println!("No saved problems - initializing a fresh set.");
+            math_quiz::init_problems(&mut problems, &config);
+        }
+    }
+    #[cfg(not(feature = "sqlite"))]
+    load_progress(&mut problems, &config);
 
     // If requested, reset progress regardless whether successfully loaded or not
     if args.reset {
         println!("Resetting progress");
-        math_quiz::init_problems(&mut problems);
+        problems.clear();
+        math_quiz::init_problems(&mut problems, &config);
         // Save new reset, ignore any problems
-        let _ = save_progress(&problems, &args.config);
+        #[cfg(feature = "sqlite")]
+        { let _ = math_quiz::sqlite::save_progress(&conn, &problems); }
+        #[cfg(not(feature = "sqlite"))]
+        { let _ = save_progress(&problems); }
     }
-    
+
     // Add more questions if requested
     match &args.cmd {
         Some(Commands::Add{question_type}) => {
             match question_type.as_str() {
-                "+" | "plus" => math_quiz::add_addition(&mut problems),
-                "-" | "minus" => math_quiz::add_subtraction(&mut problems),
-                "x" | "*" | "multiplication" => math_quiz::add_mult(&mut problems),
-                _ => eprintln!("Unknown question type to add: {}", question_type),         
+                "+" | "plus" => math_quiz::add_addition(&mut problems, &config),
+                "-" | "minus" => math_quiz::add_subtraction(&mut problems, &config),
+                "x" | "*" | "multiplication" => math_quiz::add_mult(&mut problems, &config),
+                "/" | "\u{00f7}" | "division" => math_quiz::add_division(&mut problems, &config),
+                _ => eprintln!("Unknown question type to add: {}", question_type),
             }
             // Save new questions and then quit
-            return save_progress(&problems, &args.config);        
+            #[cfg(feature = "sqlite")]
+            { math_quiz::sqlite::save_progress(&conn, &problems).expect("Unable to save problems to database"); return Ok(()); }
+            #[cfg(not(feature = "sqlite"))]
+            return save_progress(&problems);
         },
-        None => {}        
+        None => {}
     }
 
+    // Unlock the next difficulty tier for any operator already mastered
+    math_quiz::expand_tiers(&mut problems, &config);
+
     // Ensure each math operation in question set is seen at least once
     let mut op_seen = HashMap::new();
 
@@ -78,10 +113,33 @@ fn main() -> std::io::Result<()> {
     // Count total number of questions
     let mut num_questions = 0;
 
+    // Nothing to ask (e.g. a config with all-empty tiers) - bail out before the
+    // selection logic, which would otherwise divide/index by zero.
+    if problems.is_empty() {
+        eprintln!("No problems available - check your configuration.");
+        #[cfg(feature = "sqlite")]
+        { math_quiz::sqlite::save_progress(&conn, &problems).expect("Unable to save problems to database"); return Ok(()); }
+        #[cfg(not(feature = "sqlite"))]
+        return save_progress(&problems);
+    }
+
+    // Pre-plan a non-repetitive session ordering for "drill" mode
+    let order = math_quiz::sort_problems(&problems);
+    let mut order_pos = 0;
+
     // Loop until 5 consecutive correct answers in less than 2 seconds
     while num_questions<30 && (num_correct<5 || op_seen.iter().any(|x| *x.1==false)) {       
-        // Select problem based on number of times presented, number incorrect, and time to answer correctly
-        let prob = math_quiz::select_problem(&problems);
+        // Select problem: "challenge" maximizes information gain, "drill" walks the
+        // pre-planned non-repetitive ordering, "review" (default) prefers overdue items.
+        let prob = match args.mode.as_str() {
+            "challenge" => math_quiz::select_problem_adaptive(&problems),
+            "drill" => {
+                let p = order[order_pos % order.len()];
+                order_pos += 1;
+                p
+            }
+            _ => math_quiz::select_problem(&problems),
+        };
 
         // Set flag for operation seen
         op_seen.insert(problems[prob].get_op(), true);
@@ -94,7 +152,12 @@ fn main() -> std::io::Result<()> {
         // Start the timer
         let timer = Instant::now();
 
-        loop {            
+        // Grade the presentation once, from the first answer only
+        let mut attempt_no = 0;
+        let mut first_try = false;
+        let mut first_elapsed = timer.elapsed();
+
+        loop {
             // Print problem        
             print!("#{}: {}", &num_questions, problems[prob]);
             // Flush since no endline
@@ -119,7 +182,24 @@ fn main() -> std::io::Result<()> {
             };
 
             // Check and see if correct answer
-            if problems[prob].check_guess(guess, timer.elapsed()) {            
+            let elapsed = timer.elapsed();
+            let correct = problems[prob].check_guess(guess, elapsed);
+            // Remember the outcome of the first presentation for grading
+            attempt_no += 1;
+            if attempt_no == 1 {
+                first_try = correct;
+                first_elapsed = elapsed;
+            }
+            // Record this individual attempt in the history database when enabled
+            #[cfg(feature = "sqlite")]
+            math_quiz::sqlite::record_attempt(&conn, &math_quiz::sqlite::Attempt {
+                problem_id: prob as i64 + 1,
+                timestamp: std::time::SystemTime::now(),
+                guess,
+                correct,
+                elapsed,
+            }).expect("Unable to record attempt");
+            if correct {
                 println!("Correct! It took you {} seconds to solve.", problems[prob].get_time().as_secs());
                 if problems[prob].get_time().as_secs()<=2 {
                     num_correct+=1;
@@ -129,41 +209,76 @@ fn main() -> std::io::Result<()> {
                 println!("Sorry, that is not correct.");
                 // Reset culmulative counter
                 num_correct=0;
-            }            
-        }        
+            }
+        }
+
+        // Apply exactly one SM-2 update for the whole presentation
+        problems[prob].grade(first_try, first_elapsed);
     }
-   
+
     println!("Congratulations! You have finished for today.");
-    return save_progress(&problems, &args.config);
+
+    // Persist to the active store - the database when sqlite is enabled, else JSON
+    #[cfg(feature = "sqlite")]
+    { math_quiz::sqlite::save_progress(&conn, &problems).expect("Unable to save problems to database"); return Ok(()); }
+    #[cfg(not(feature = "sqlite"))]
+    return save_progress(&problems);
 }
 
-/// Load question bank from file, and reset if any errors encountered
-fn load_progress(problems: &mut Vec<Problem>, path: &Option<PathBuf>) {
+/// Path the progress blob is stored at
+#[cfg(not(feature = "sqlite"))]
+fn progress_path() -> PathBuf {
+    return PathBuf::from("math_quiz.ini");
+}
+
+/// Load the difficulty configuration from the `--config` file, falling back to
+/// the built-in defaults when no file is given or it cannot be parsed.
+fn load_config(path: &Option<PathBuf>) -> Config {
     let path = match path {
-        Some(path) => path.clone(),
-        None => PathBuf::from("math_quiz.ini"),
+        Some(path) => path,
+        None => return Config::default(),
     };
+    match fs::read_to_string(path) {
+        Ok(contents) => match serde_json::from_str(&contents) {
+            Ok(config) => {
+                println!("Reading difficulty config from {}", path.display());
+                config
+            }
+            Err(err) => {
+                eprintln!("Invalid config file ({}) - using defaults", err);
+                Config::default()
+            }
+        },
+        Err(_) => {
+            eprintln!("Unable to open config file - using defaults");
+            Config::default()
+        }
+    }
+}
+
+/// Load question bank from file, and reset if any errors encountered
+#[cfg(not(feature = "sqlite"))]
+fn load_progress(problems: &mut Vec<Problem>, config: &Config) {
+    let path = progress_path();
 
     // Read entire file into string
     let saved_progress = fs::read_to_string(&path);
-    // Replace problems with config file; initialize if any errors encountered
-    if saved_progress.is_ok() {        
+    // Replace problems with saved progress; initialize if any errors encountered
+    if saved_progress.is_ok() {
         println!("Reading progress from {}", path.display());
         let new_problems: Vec<Problem> = serde_json::from_str(&saved_progress.unwrap()).expect("Error deserializing problems");
         *problems=new_problems;
-    } else {        
+    } else {
         println!("Unable to open progress file - resetting.");
-        // Start with list of problems        
-        math_quiz::init_problems(problems);            
+        // Start with list of problems
+        math_quiz::init_problems(problems, config);
     }
 }
 
-/// Save progress to specified file or math_quiz.ini if not specified
-fn save_progress(problems: &Vec<Problem>, path: &Option<PathBuf>) -> io::Result<()> {    
-    let path = match path {
-        Some(path) => path.clone(),
-        None => PathBuf::from("math_quiz.ini"),
-    };
+/// Save progress to the progress file
+#[cfg(not(feature = "sqlite"))]
+fn save_progress(problems: &Vec<Problem>) -> io::Result<()> {
+    let path = progress_path();
     println!("Saving progress to {}", path.display());
     // Save progress
     return Ok(fs::write(path, serde_json::to_string(&problems).expect("Error serializing problems")).expect("Error saving progress file"));
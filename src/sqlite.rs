@@ -0,0 +1,187 @@
+//! SQLite persistence backend
+//!
+//! An optional store (enabled with the `sqlite` feature) that keeps one row per
+//! [`Problem`] and, unlike the flat JSON blob, records every individual attempt
+//! in a separate `attempts` table. The schema is advanced by ordered migrations
+//! applied at startup so it can evolve over time.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rusqlite::{Connection, params};
+use super::{MathOp, Problem};
+
+/// A single recorded answer to a problem
+#[derive(Debug)]
+pub struct Attempt {
+    /// Problem this attempt belongs to
+    pub problem_id: i64,
+    /// When the attempt was made
+    pub timestamp: SystemTime,
+    /// The number the student entered
+    pub guess: u16,
+    /// Whether the guess matched the answer
+    pub correct: bool,
+    /// Time taken to answer
+    pub elapsed: Duration,
+}
+
+/// Ordered up-migrations. Append new scripts; never edit or reorder applied ones.
+const MIGRATIONS: &[&str] = &[
+    // M1 - initial schema
+    "CREATE TABLE problems (
+        id INTEGER PRIMARY KEY,
+        operand0 INTEGER NOT NULL,
+        operand1 INTEGER NOT NULL,
+        operator TEXT NOT NULL,
+        answer INTEGER NOT NULL,
+        num_wrong INTEGER NOT NULL,
+        latest_time_secs INTEGER NOT NULL,
+        ef REAL NOT NULL,
+        reps INTEGER NOT NULL,
+        interval INTEGER NOT NULL,
+        last_reviewed_secs INTEGER NOT NULL
+    );
+    CREATE TABLE attempts (
+        id INTEGER PRIMARY KEY,
+        problem_id INTEGER NOT NULL REFERENCES problems(id),
+        timestamp_secs INTEGER NOT NULL,
+        guess INTEGER NOT NULL,
+        correct INTEGER NOT NULL,
+        elapsed_secs INTEGER NOT NULL
+    );",
+    // M2 - record the difficulty tier each problem was generated from
+    "ALTER TABLE problems ADD COLUMN tier INTEGER NOT NULL DEFAULT 0;",
+];
+
+/// Apply any migrations not yet recorded in the `migrations` table.
+pub fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS migrations (id INTEGER PRIMARY KEY, applied_at_secs INTEGER NOT NULL)",
+        [],
+    )?;
+    let applied: i64 = conn.query_row("SELECT COUNT(*) FROM migrations", [], |r| r.get(0))?;
+    for (idx, script) in MIGRATIONS.iter().enumerate().skip(applied as usize) {
+        conn.execute_batch(script)?;
+        conn.execute(
+            "INSERT INTO migrations (id, applied_at_secs) VALUES (?1, ?2)",
+            params![idx as i64 + 1, secs_since_epoch(SystemTime::now())],
+        )?;
+    }
+    return Ok(());
+}
+
+/// Load every stored problem, ordered by id.
+pub fn load_progress(conn: &Connection) -> rusqlite::Result<Vec<Problem>> {
+    let mut stmt = conn.prepare(
+        "SELECT operand0, operand1, operator, answer, num_wrong, latest_time_secs, \
+         ef, reps, interval, last_reviewed_secs, tier FROM problems ORDER BY id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(Problem {
+            operands: [row.get(0)?, row.get(1)?],
+            operator: op_from_str(&row.get::<_, String>(2)?),
+            answer: row.get(3)?,
+            num_wrong: row.get(4)?,
+            latest_time: Duration::from_secs(row.get::<_, i64>(5)? as u64),
+            ef: row.get(6)?,
+            reps: row.get(7)?,
+            interval: row.get(8)?,
+            last_reviewed: UNIX_EPOCH + Duration::from_secs(row.get::<_, i64>(9)? as u64),
+            tier: row.get::<_, i64>(10)? as usize,
+        })
+    })?;
+    let mut problems = Vec::new();
+    for p in rows {
+        problems.push(p?);
+    }
+    return Ok(problems);
+}
+
+/// Persist the current problem set, replacing any existing rows. Problem ids are
+/// assigned by insertion order so they line up with [`problem_history`] keys.
+pub fn save_progress(conn: &Connection, problems: &[Problem]) -> rusqlite::Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM problems", [])?;
+    for (idx, p) in problems.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO problems (id, operand0, operand1, operator, answer, num_wrong, \
+             latest_time_secs, ef, reps, interval, last_reviewed_secs, tier) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                idx as i64 + 1,
+                p.operands[0] as i64,
+                p.operands[1] as i64,
+                op_to_str(p.operator),
+                p.answer as i64,
+                p.num_wrong,
+                p.latest_time.as_secs() as i64,
+                p.ef,
+                p.reps,
+                p.interval,
+                secs_since_epoch(p.last_reviewed),
+                p.tier as i64,
+            ],
+        )?;
+    }
+    tx.commit()?;
+    return Ok(());
+}
+
+/// Record one attempt against a problem.
+pub fn record_attempt(conn: &Connection, attempt: &Attempt) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO attempts (problem_id, timestamp_secs, guess, correct, elapsed_secs) \
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            attempt.problem_id,
+            secs_since_epoch(attempt.timestamp),
+            attempt.guess,
+            attempt.correct as i64,
+            attempt.elapsed.as_secs() as i64,
+        ],
+    )?;
+    return Ok(());
+}
+
+/// Return every attempt recorded for a problem, oldest first.
+pub fn problem_history(conn: &Connection, problem_id: i64) -> rusqlite::Result<Vec<Attempt>> {
+    let mut stmt = conn.prepare(
+        "SELECT timestamp_secs, guess, correct, elapsed_secs FROM attempts \
+         WHERE problem_id = ?1 ORDER BY timestamp_secs",
+    )?;
+    let rows = stmt.query_map(params![problem_id], |row| {
+        Ok(Attempt {
+            problem_id,
+            timestamp: UNIX_EPOCH + Duration::from_secs(row.get::<_, i64>(0)? as u64),
+            guess: row.get(1)?,
+            correct: row.get::<_, i64>(2)? != 0,
+            elapsed: Duration::from_secs(row.get::<_, i64>(3)? as u64),
+        })
+    })?;
+    let mut attempts = Vec::new();
+    for a in rows {
+        attempts.push(a?);
+    }
+    return Ok(attempts);
+}
+
+fn secs_since_epoch(t: SystemTime) -> i64 {
+    return t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+}
+
+fn op_to_str(op: MathOp) -> &'static str {
+    return match op {
+        MathOp::Plus => "plus",
+        MathOp::Minus => "minus",
+        MathOp::Multiply => "multiply",
+        MathOp::Divide => "divide",
+    };
+}
+
+fn op_from_str(s: &str) -> MathOp {
+    return match s {
+        "minus" => MathOp::Minus,
+        "multiply" => MathOp::Multiply,
+        "divide" => MathOp::Divide,
+        _ => MathOp::Plus,
+    };
+}